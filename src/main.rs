@@ -1,22 +1,142 @@
 use ::{
     anyhow::Context,
-    backoff::{ future::retry, ExponentialBackoff },
     dotenvy::dotenv,
-    futures::{ future::TryFutureExt, stream::StreamExt },
+    futures::{ sink::SinkExt, stream::StreamExt, FutureExt },
     log::{ error, info },
-    std::{ collections::HashMap, env, sync::Arc, time::{ Duration, SystemTime } },
-    tokio::sync::Mutex,
+    std::{
+        collections::{ HashMap, HashSet, VecDeque },
+        env,
+        sync::{ atomic::{ AtomicU64, Ordering }, Arc },
+        time::{ Duration, Instant, SystemTime },
+    },
+    tokio::sync::{ mpsc, watch, Mutex },
     yellowstone_grpc_client::{ ClientTlsConfig, GeyserGrpcClient, Interceptor },
     yellowstone_grpc_proto::{
-        prelude::{ SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeRequestFilterSlots },
+        prost::Message as _,
+        prelude::{
+            subscribe_request_filter_accounts_filter::Filter as AccountsFilterOneof,
+            subscribe_update::UpdateOneof,
+            CommitmentLevel,
+            SubscribeRequest,
+            SubscribeRequestFilterAccounts,
+            SubscribeRequestFilterAccountsFilter,
+            SubscribeRequestFilterBlocks,
+            SubscribeRequestFilterSlots,
+            SubscribeRequestFilterTransactions,
+            SubscribeRequestPing,
+            SubscribeUpdate,
+        },
         tonic::transport::Certificate,
     },
 };
 
+// 跨多个端点去重时保留的槽位窗口：早于 `latest_slot - K` 的标识会被丢弃，保持内存恒定
+const DEDUP_SLOT_WINDOW: u64 = 512;
+
+// 指标摘要的默认打印间隔（秒），可由 METRICS_INTERVAL_SECS 覆盖
+const METRICS_INTERVAL_SECS: u64 = 10;
+
+// 延迟直方图的桶边界（毫秒，升序），用于估算 p50/p90/p99
+const LATENCY_BUCKETS_MS: [f64; 11] = [
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+];
+
+// 运行期指标：消息/字节计数、延迟直方图与重连统计，由订阅循环写入、后台任务周期读出
+#[derive(Default)]
+struct Stats {
+    // 累计收到的消息数
+    messages: u64,
+    // 累计收到的编码字节数
+    bytes: u64,
+    // 累计重连次数
+    reconnects: u64,
+    // 延迟直方图：落在每个桶内的消息数，最后一个桶为溢出桶（> 最大边界）
+    latency_hist: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    // 累计延迟（毫秒），用于计算平均值
+    total_latency_ms: f64,
+}
+
+impl Stats {
+    // 记录一条消息：其编码字节数与相对 created_at 的到达延迟（毫秒）
+    fn record_message(&mut self, bytes: usize, lag_ms: f64) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+        self.total_latency_ms += lag_ms;
+        let idx = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|boundary| lag_ms <= *boundary)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.latency_hist[idx] += 1;
+    }
+
+    // 记录一次重连
+    fn record_reconnect(&mut self) {
+        self.reconnects += 1;
+    }
+
+    // 由直方图估算分位数，返回对应桶的上界标签（溢出桶返回 ">max"）
+    fn percentile(&self, p: f64) -> String {
+        if self.messages == 0 {
+            return "n/a".to_string();
+        }
+        let target = (p * (self.messages as f64)).ceil() as u64;
+        let mut cumulative = 0u64;
+        for (idx, count) in self.latency_hist.iter().enumerate() {
+            cumulative += *count;
+            if cumulative >= target {
+                return match LATENCY_BUCKETS_MS.get(idx) {
+                    Some(boundary) => format!("{boundary}ms"),
+                    None => format!(">{}ms", LATENCY_BUCKETS_MS[LATENCY_BUCKETS_MS.len() - 1]),
+                };
+            }
+        }
+        format!(">{}ms", LATENCY_BUCKETS_MS[LATENCY_BUCKETS_MS.len() - 1])
+    }
+}
+
+// 后台任务：每隔固定间隔读取 Stats，打印一行消息/吞吐/延迟分位数摘要
+fn spawn_metrics_reporter(stats: Arc<Mutex<Stats>>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let (mut prev_messages, mut prev_bytes) = (0u64, 0u64);
+        let mut last_tick = Instant::now();
+        loop {
+            ticker.tick().await;
+            let elapsed = last_tick.elapsed().as_secs_f64().max(f64::MIN_POSITIVE);
+            last_tick = Instant::now();
+
+            let stats = stats.lock().await;
+            let msg_rate = ((stats.messages - prev_messages) as f64) / elapsed;
+            let byte_rate = ((stats.bytes - prev_bytes) as f64) / elapsed;
+            let avg_latency = if stats.messages == 0 {
+                0.0
+            } else {
+                stats.total_latency_ms / (stats.messages as f64)
+            };
+            info!(
+                "Metrics: {:.0} msg/s, {:.0} B/s, latency avg {:.1}ms p50 {} p90 {} p99 {}, {} reconnects, {} total msgs",
+                msg_rate,
+                byte_rate,
+                avg_latency,
+                stats.percentile(0.50),
+                stats.percentile(0.90),
+                stats.percentile(0.99),
+                stats.reconnects,
+                stats.messages
+            );
+            prev_messages = stats.messages;
+            prev_bytes = stats.bytes;
+        }
+    });
+}
+
 #[derive(Debug, Clone)]
 struct Config {
     // 端点
     endpoint: String,
+    // 冗余订阅的端点列表（来自 YELLOWSTONE_GRPC_URLS，逗号分隔），非空时取代 endpoint；为空表示单端点模式
+    endpoints: Vec<String>,
     // cs证书
     ca_certificate: Option<String>,
     // x_token
@@ -52,10 +172,145 @@ struct Config {
     // 请求超时时间（单位：毫秒）
     timeout_ms: Option<u64>,
 
+    // 应用层 ping 发送间隔（单位：毫秒），未设置则不主动发送 ping
+    ping_interval_ms: Option<u64>,
+
+    // 无任何流量（含 pong）的容忍窗口（单位：毫秒），超时则主动断开触发重连
+    ping_timeout_ms: Option<u64>,
+
+    // 订阅过滤配置（账户/owner/数据大小/commitment/交易与区块开关），为空时回退到订阅全部
+    filters: FilterConfig,
+
     // 最大解码消息尺寸（防止内存溢出），默认1GiB
     max_decoding_message_size: usize,
 }
 
+// 订阅过滤配置：允许用户声明只关心的账户/owner 等，而无需改代码重新编译
+#[derive(Debug, Clone, Default)]
+struct FilterConfig {
+    // 指定账户 pubkey 列表（base58）
+    accounts: Vec<String>,
+    // 指定 owner 程序 ID 列表（base58）
+    owners: Vec<String>,
+    // account_data_size 过滤（字节）
+    account_data_size: Option<u64>,
+    // 仅保留带非空交易签名的账户更新
+    nonempty_txn_signature: Option<bool>,
+    // commitment 级别（processed/confirmed/finalized）
+    commitment: Option<i32>,
+    // 是否订阅交易
+    include_transactions: bool,
+    // 是否订阅区块
+    include_blocks: bool,
+}
+
+impl FilterConfig {
+    fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            accounts: parse_pubkey_list("SUBSCRIBE_ACCOUNTS")?,
+            owners: parse_pubkey_list("SUBSCRIBE_OWNERS")?,
+            account_data_size: env
+                ::var("SUBSCRIBE_ACCOUNT_DATA_SIZE")
+                .ok()
+                .map(|s| {
+                    s.parse().map_err(|_|
+                        anyhow::anyhow!("invalid SUBSCRIBE_ACCOUNT_DATA_SIZE: {s}")
+                    )
+                })
+                .transpose()?,
+            nonempty_txn_signature: env
+                ::var("SUBSCRIBE_NONEMPTY_TXN_SIGNATURE")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            commitment: env
+                ::var("COMMITMENT")
+                .ok()
+                .map(|s| parse_commitment(&s))
+                .transpose()?,
+            include_transactions: env
+                ::var("INCLUDE_TRANSACTIONS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(false),
+            include_blocks: env
+                ::var("INCLUDE_BLOCKS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(false),
+        })
+    }
+
+    // 未声明任何账户/owner/数据大小过滤时视为空，回退到订阅全部账户
+    fn accounts_unfiltered(&self) -> bool {
+        self.accounts.is_empty() && self.owners.is_empty() && self.account_data_size.is_none()
+    }
+}
+
+// 解析逗号分隔的 base58 pubkey 列表，遇到非法条目立即以清晰的错误失败
+fn parse_pubkey_list(var: &str) -> anyhow::Result<Vec<String>> {
+    let raw = match env::var(var) {
+        Ok(raw) => raw,
+        Err(_) => return Ok(vec![]),
+    };
+    raw.split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            validate_pubkey(s).with_context(|| format!("invalid pubkey in {var}: {s}"))?;
+            Ok(s.to_string())
+        })
+        .collect()
+}
+
+// 将 commitment 字符串解析为 proto 枚举值
+fn parse_commitment(s: &str) -> anyhow::Result<i32> {
+    let level = match s.trim().to_ascii_lowercase().as_str() {
+        "processed" => CommitmentLevel::Processed,
+        "confirmed" => CommitmentLevel::Confirmed,
+        "finalized" => CommitmentLevel::Finalized,
+        other => anyhow::bail!("invalid COMMITMENT '{other}' (expected processed/confirmed/finalized)"),
+    };
+    Ok(level as i32)
+}
+
+// 校验 base58 pubkey：解码后必须为 32 字节
+fn validate_pubkey(s: &str) -> anyhow::Result<()> {
+    let decoded = base58_decode(s)?;
+    anyhow::ensure!(decoded.len() == 32, "pubkey must decode to 32 bytes, got {}", decoded.len());
+    Ok(())
+}
+
+// 最小化 base58 解码器（Bitcoin 字母表），仅用于入参校验，避免引入额外依赖
+fn base58_decode(input: &str) -> anyhow::Result<Vec<u8>> {
+    const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in input.bytes() {
+        let mut carry = ALPHABET
+            .iter()
+            .position(|&b| b == c)
+            .ok_or_else(|| anyhow::anyhow!("invalid base58 character '{}'", c as char))?;
+        for byte in bytes.iter_mut() {
+            carry += (*byte as usize) * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+    // 前导 '1' 对应前导零字节
+    for c in input.bytes() {
+        if c == b'1' {
+            bytes.push(0);
+        } else {
+            break;
+        }
+    }
+    bytes.reverse();
+    Ok(bytes)
+}
+
 impl Config {
     fn from_env() -> anyhow::Result<Self> {
         dotenv().ok(); // 加载.env文件，忽略错误如果文件不存在
@@ -64,6 +319,16 @@ impl Config {
             endpoint: env
                 ::var("YELLOWSTONE_GRPC_URL")
                 .unwrap_or_else(|_| "http://127.0.0.1:10000".into()),
+            endpoints: env
+                ::var("YELLOWSTONE_GRPC_URLS")
+                .ok()
+                .map(|s| {
+                    s.split(',')
+                        .map(|e| e.trim().to_string())
+                        .filter(|e| !e.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
             ca_certificate: env::var("CA_CERTIFICATE").ok(),
             x_token: env::var("X_TOKEN").ok(),
             connect_timeout_ms: env
@@ -110,6 +375,15 @@ impl Config {
                 ::var("TIMEOUT_MS")
                 .ok()
                 .and_then(|s| s.parse().ok()),
+            ping_interval_ms: env
+                ::var("PING_INTERVAL_MS")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            ping_timeout_ms: env
+                ::var("PING_TIMEOUT_MS")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            filters: FilterConfig::from_env()?,
             max_decoding_message_size: env
                 ::var("MAX_DECODING_MESSAGE_SIZE")
                 .map(|s| s.parse().unwrap_or(1024 * 1024 * 1024))
@@ -117,6 +391,15 @@ impl Config {
         })
     }
 
+    // 返回本次运行要连接的全部端点：配置了端点列表则使用列表，否则回退到单端点
+    fn all_endpoints(&self) -> Vec<String> {
+        if self.endpoints.is_empty() {
+            vec![self.endpoint.clone()]
+        } else {
+            self.endpoints.clone()
+        }
+    }
+
     async fn connect(&self) -> anyhow::Result<GeyserGrpcClient<impl Interceptor>> {
         let mut tls_config = ClientTlsConfig::new().with_native_roots();
         if let Some(path) = &self.ca_certificate {
@@ -166,34 +449,144 @@ impl Config {
     }
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    unsafe {
-        env::set_var(
-            env_logger::DEFAULT_FILTER_ENV,
-            env::var_os(env_logger::DEFAULT_FILTER_ENV).unwrap_or_else(|| "info".into())
-        );
+// 一条更新的稳定标识，用于跨端点去重：先到的端点胜出，后到的重复项被丢弃
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum UpdateKey {
+    // 账户更新按 (pubkey, write_version, slot) 唯一
+    Account(Vec<u8>, u64, u64),
+    // 槽位更新按 (slot, status) 唯一
+    Slot(u64, i32),
+}
+
+impl UpdateKey {
+    // 该标识所属的槽位，用于按水位线裁剪去重集合
+    fn slot(&self) -> u64 {
+        match self {
+            UpdateKey::Account(_, _, slot) => *slot,
+            UpdateKey::Slot(slot, _) => *slot,
+        }
     }
+}
 
-    // 初始化日志
-    env_logger::init();
+// 更新所属的槽位，用于断线重连时记录已消费到的进度；ping/pong 等无槽位更新返回 None
+fn message_slot(update: &SubscribeUpdate) -> Option<u64> {
+    match update.update_oneof.as_ref()? {
+        UpdateOneof::Account(account) => Some(account.slot),
+        UpdateOneof::Slot(slot) => Some(slot.slot),
+        UpdateOneof::Transaction(tx) => Some(tx.slot),
+        UpdateOneof::TransactionStatus(tx) => Some(tx.slot),
+        UpdateOneof::Block(block) => Some(block.slot),
+        UpdateOneof::BlockMeta(meta) => Some(meta.slot),
+        UpdateOneof::Entry(entry) => Some(entry.slot),
+        _ => None,
+    }
+}
 
-    // 初始化配置
-    let config = Config::from_env()?;
-    let zero_attempts = Arc::new(Mutex::new(true));
+// 为可去重的更新类型计算标识；无法稳定标识的更新（如 ping/pong）返回 None 直接放行
+fn update_identity(update: &SubscribeUpdate) -> Option<UpdateKey> {
+    match update.update_oneof.as_ref()? {
+        UpdateOneof::Account(account) => {
+            let info = account.account.as_ref()?;
+            Some(UpdateKey::Account(info.pubkey.clone(), info.write_version, account.slot))
+        }
+        UpdateOneof::Slot(slot) => Some(UpdateKey::Slot(slot.slot, slot.status)),
+        _ => None,
+    }
+}
 
-    // 构造订阅请求（订阅所有账户和槽位更新）
-    let request = SubscribeRequest {
-        accounts: {
-            let mut map = HashMap::new();
-            map.insert("all".to_string(), SubscribeRequestFilterAccounts {
-                nonempty_txn_signature: None,
-                account: vec![],
-                owner: vec![],
-                filters: vec![],
+// 有界去重集合：按槽位水位线裁剪，任何早于 `latest_slot - window` 的标识都会被逐出，内存保持恒定
+struct DedupSet {
+    seen: HashSet<UpdateKey>,
+    order: VecDeque<UpdateKey>,
+    latest_slot: u64,
+    window: u64,
+}
+
+impl DedupSet {
+    fn new(window: u64) -> Self {
+        Self {
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+            latest_slot: 0,
+            window,
+        }
+    }
+
+    // 记录一个标识；若此前从未见过则返回 true（应当发出），否则返回 false（重复，丢弃）
+    fn observe(&mut self, key: UpdateKey) -> bool {
+        self.latest_slot = self.latest_slot.max(key.slot());
+        self.prune();
+        if self.seen.insert(key.clone()) {
+            self.order.push_back(key);
+            true
+        } else {
+            false
+        }
+    }
+
+    // 逐出早于水位线的标识
+    fn prune(&mut self) {
+        let watermark = self.latest_slot.saturating_sub(self.window);
+        while let Some(front) = self.order.front() {
+            if front.slot() < watermark {
+                let stale = self.order.pop_front().expect("front exists");
+                self.seen.remove(&stale);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+// 根据过滤配置构造订阅请求；未声明任何过滤时回退到订阅全部账户与槽位
+fn build_request(filters: &FilterConfig) -> SubscribeRequest {
+    let mut accounts = HashMap::new();
+    if filters.accounts_unfiltered() {
+        accounts.insert("all".to_string(), SubscribeRequestFilterAccounts {
+            nonempty_txn_signature: filters.nonempty_txn_signature,
+            account: vec![],
+            owner: vec![],
+            filters: vec![],
+        });
+    } else {
+        let mut data_filters = vec![];
+        if let Some(size) = filters.account_data_size {
+            data_filters.push(SubscribeRequestFilterAccountsFilter {
+                filter: Some(AccountsFilterOneof::Datasize(size)),
             });
-            map
-        },
+        }
+        accounts.insert("accounts".to_string(), SubscribeRequestFilterAccounts {
+            nonempty_txn_signature: filters.nonempty_txn_signature,
+            account: filters.accounts.clone(),
+            owner: filters.owners.clone(),
+            filters: data_filters,
+        });
+    }
+
+    let mut transactions = HashMap::new();
+    if filters.include_transactions {
+        transactions.insert("transactions".to_string(), SubscribeRequestFilterTransactions {
+            vote: None,
+            failed: None,
+            signature: None,
+            account_include: vec![],
+            account_exclude: vec![],
+            account_required: vec![],
+        });
+    }
+
+    let mut blocks = HashMap::new();
+    if filters.include_blocks {
+        blocks.insert("blocks".to_string(), SubscribeRequestFilterBlocks {
+            account_include: vec![],
+            include_transactions: Some(true),
+            include_accounts: Some(false),
+            include_entries: Some(false),
+        });
+    }
+
+    SubscribeRequest {
+        accounts,
         slots: {
             let mut map = HashMap::new();
             map.insert("all".to_string(), SubscribeRequestFilterSlots {
@@ -202,53 +595,533 @@ async fn main() -> anyhow::Result<()> {
             });
             map
         },
+        transactions,
+        blocks,
+        commitment: filters.commitment,
         ..Default::default()
+    }
+}
+
+// 有界带抖动的重连策略：限定最大重试次数、退避上下限与抖动因子，避免无限重连与惊群
+#[derive(Debug, Clone)]
+struct RetryPolicy {
+    // 最大重试次数（超过即放弃并向上报错）
+    max_retries: usize,
+    // 初始退避
+    initial_backoff: Duration,
+    // 退避上限
+    max_backoff: Duration,
+    // 抖动因子 [0, 1]：1.0 即 full-jitter（在 [0, cap] 区间均匀取值）
+    jitter: f64,
+}
+
+impl RetryPolicy {
+    fn from_env() -> Self {
+        Self {
+            max_retries: env
+                ::var("MAX_RETRIES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10),
+            initial_backoff: Duration::from_millis(
+                env::var("INITIAL_BACKOFF_MS").ok().and_then(|s| s.parse().ok()).unwrap_or(500)
+            ),
+            max_backoff: Duration::from_millis(
+                env::var("MAX_BACKOFF_MS").ok().and_then(|s| s.parse().ok()).unwrap_or(30_000)
+            ),
+            jitter: env
+                ::var("JITTER_FACTOR")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1.0_f64)
+                .clamp(0.0, 1.0),
+        }
+    }
+
+    // 第 `attempt` 次重试（从 0 起）的退避时长：random(cap*(1-jitter), cap)，
+    // 其中 cap = min(max_backoff, initial_backoff * 2^attempt)
+    fn backoff_delay(&self, attempt: u32) -> Duration {
+        let base = (self.initial_backoff.as_millis() as f64) * 2f64.powi(attempt as i32);
+        let cap = base.min(self.max_backoff.as_millis() as f64);
+        let low = cap * (1.0 - self.jitter);
+        let millis = low + (cap - low) * jitter_fraction();
+        Duration::from_millis(millis as u64)
+    }
+}
+
+// 判断错误是否为致命（不可重试）：仅认证/授权类配置错误快速失败，
+// 连接重置、超时、TLS 握手抖动等瞬时错误继续重试。
+// 注意 InvalidArgument 不算致命——从已被裁剪的 from_slot 续传就会返回它，
+// 属于可恢复条件（下次重连会以新的 from_slot 或空 from_slot 重试）。
+fn is_permanent_error(error: &anyhow::Error) -> bool {
+    let message = format!("{error:#}").to_ascii_lowercase();
+    // 匹配 gRPC 状态码名（tonic 的 Display/Debug 均会带上 code 名），
+    // 而非 "tls"/"certificate" 这类会误伤瞬时握手错误的宽泛子串
+    const FATAL_CODES: [&str; 3] = ["unauthenticated", "permissiondenied", "permission denied"];
+    FATAL_CODES.iter().any(|marker| message.contains(marker))
+}
+
+// 线程级（thread_local 状态）伪随机分数 [0, 1)，用于退避抖动；采用 xorshift 自举，避免引入 rand 依赖
+fn jitter_fraction() -> f64 {
+    use std::cell::Cell;
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(0);
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        if x == 0 {
+            // 首次以当前时间的纳秒作为种子
+            x = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0x9e3779b97f4a7c15)
+                | 1;
+        }
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x >> 11) as f64 / ((1u64 << 53) as f64)
+    })
+}
+
+// 一次连接的终止原因：仅这两种属于"干净停止"，其余情况以错误上报交由重连策略处理
+enum ConnectionOutcome {
+    // 收到关闭信号
+    Shutdown,
+    // 下游合并通道已关闭
+    ChannelClosed,
+}
+
+// 处理单条更新：记录进度与指标，再按 sink 发出（直接打印或转发到合并通道）。
+// 返回 false 表示下游通道已关闭，调用方应停止本端点。
+async fn emit_update(
+    msg: SubscribeUpdate,
+    sink: &Option<mpsc::Sender<SubscribeUpdate>>,
+    stats: &Arc<Mutex<Stats>>,
+    last_seen_slot: &AtomicU64
+) -> anyhow::Result<bool> {
+    // 记录已消费到的最高槽位，供重连续传使用
+    if let Some(slot) = message_slot(&msg) {
+        last_seen_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    // created_at 仅在数据帧上出现；pong 等控制/保活帧没有时间戳，
+    // 此时按 0ms 延迟记入统计（计入直方图与平均值），而不是报错，避免误判为断线触发重连
+    let lag_ms = match msg.created_at {
+        Some(created_at) => {
+            let created_at: SystemTime = created_at
+                .try_into()
+                .context("failed to parse created_at")?;
+            SystemTime::now().duration_since(created_at).unwrap_or_default().as_secs_f64() * 1000.0
+        }
+        None => 0.0,
     };
+    stats.lock().await.record_message(msg.encoded_len(), lag_ms);
 
-    retry(ExponentialBackoff::default(), move || {
-        let config = config.clone();
-        let request = request.clone();
-        let zero_attempts = Arc::clone(&zero_attempts);
-
-        (
-            async move {
-                let mut zero_attempts = zero_attempts.lock().await;
-                if *zero_attempts {
-                    *zero_attempts = false;
-                } else {
-                    info!("Retry to connect to the server");
+    match sink {
+        // 合并模式：转发到去重通道，由消费端统一打印
+        Some(sink) => {
+            if sink.send(msg).await.is_err() {
+                info!("Merge channel closed, stopping endpoint");
+                return Ok(false);
+            }
+        }
+        // 单端点模式：直接打印原始消息
+        None => {
+            info!("Received update: {:?}", msg);
+        }
+    }
+    Ok(true)
+}
+
+// 单次连接的生命周期：建链、订阅、消费直到流中断或收到关闭信号。
+// 返回 Ok(ConnectionOutcome) 表示干净停止；返回 Err 表示断线，交由上层策略决定是否重连。
+async fn run_once(
+    config: &Config,
+    request: &SubscribeRequest,
+    sink: &Option<mpsc::Sender<SubscribeUpdate>>,
+    stats: &Arc<Mutex<Stats>>,
+    last_seen_slot: &AtomicU64,
+    shutdown: &mut watch::Receiver<bool>
+) -> anyhow::Result<ConnectionOutcome> {
+    let mut client = config.connect().await?;
+    info!("Connected to {}", config.endpoint);
+
+    // 从断点续传：服务端会回放 from_slot 及其之后的更新，避免丢失断线期间的消息
+    let mut request = request.clone();
+    let resume_slot = last_seen_slot.load(Ordering::Relaxed);
+    if resume_slot > 0 {
+        request.from_slot = Some(resume_slot);
+        info!("Resuming {} from slot {}", config.endpoint, resume_slot);
+    }
+
+    let (subscribe_tx, mut stream) = client
+        .subscribe_with_request(Some(request)).await
+        .context("failed to open subscribe stream")?;
+    info!("Stream opened");
+
+    // 应用层保活：周期性向订阅通道发送仅含 ping 的请求，促使服务端回 pong，
+    // 配合下方的流量超时可以比传输层心跳更快地发现半开的死连接
+    if let Some(interval_ms) = config.ping_interval_ms {
+        let mut subscribe_tx = subscribe_tx;
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_millis(interval_ms));
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                ticker.tick().await;
+                let ping = SubscribeRequest {
+                    ping: Some(SubscribeRequestPing { id: 1 }),
+                    ..Default::default()
+                };
+                if subscribe_tx.send(ping).await.is_err() {
+                    break; // 流已关闭，停止发送
                 }
-                drop(zero_attempts);
-
-                let mut client = config.connect().await.map_err(backoff::Error::transient)?;
-                info!("Connected to {}", config.endpoint);
-
-                let (mut subscribe_tx, mut stream) = client
-                    .subscribe_with_request(Some(request.clone())).await
-                    .expect("订阅失败");
-                info!("Stream opened");
-
-                while let Some(message) = stream.next().await {
-                    match message {
-                        Ok(msg) => {
-                            let created_at: SystemTime = msg.created_at
-                                .ok_or(anyhow::anyhow!("no created_at in the message"))?
-                                .try_into()
-                                .context("failed to parse created_at")?;
-
-                            // 直接打印原始消息
-                            info!("Received update: {:?}", msg);
-                        }
-                        Err(error) => {
-                            error!("Stream error: {:?}", error);
-                            break;
-                        }
-                    }
+            }
+        });
+    }
+
+    // 记录本次连接的起点，用于统计首包到达时间（time-to-first-message）
+    let connected_at = Instant::now();
+    let mut first_message = true;
+    // 未配置 ping 超时时使用一个足够大的窗口，使 select! 分支始终成立
+    let idle_timeout = config
+        .ping_timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or_else(|| Duration::from_secs(24 * 60 * 60));
+
+    loop {
+        tokio::select! {
+            // 关闭信号优先：排空当前已就绪的缓冲消息后干净退出，不再重连
+            biased;
+            _ = shutdown.changed() => {
+                info!("Shutdown requested, draining {}", config.endpoint);
+                while let Some(Some(Ok(msg))) = stream.next().now_or_never() {
+                    emit_update(msg, sink, stats, last_seen_slot).await?;
+                }
+                return Ok(ConnectionOutcome::Shutdown);
+            }
+            // 每收到一条消息（含 pong）即视为有流量并重置超时；窗口内零流量则报错触发重连
+            result = tokio::time::timeout(idle_timeout, stream.next()) => {
+                let message = result.map_err(|_|
+                    anyhow::anyhow!(
+                        "no traffic from {} within {}ms",
+                        config.endpoint,
+                        idle_timeout.as_millis()
+                    )
+                )?;
+                let Some(message) = message else {
+                    anyhow::bail!("stream closed by {}", config.endpoint);
+                };
+                let msg = message.context("stream error")?;
+                if first_message {
+                    first_message = false;
+                    info!(
+                        "Time-to-first-message on {}: {:.1}ms",
+                        config.endpoint,
+                        connected_at.elapsed().as_secs_f64() * 1000.0
+                    );
+                }
+                if !emit_update(msg, sink, stats, last_seen_slot).await? {
+                    return Ok(ConnectionOutcome::ChannelClosed);
+                }
+            }
+        }
+    }
+}
+
+// 单个端点的订阅循环：按有界带抖动的重连策略重试，致命错误快速失败，收到关闭信号干净退出
+async fn run_endpoint(
+    config: Config,
+    request: SubscribeRequest,
+    sink: Option<mpsc::Sender<SubscribeUpdate>>,
+    stats: Arc<Mutex<Stats>>,
+    shutdown: watch::Receiver<bool>,
+    policy: RetryPolicy
+) -> anyhow::Result<()> {
+    // 已观测到的最高槽位，用于断线重连时从断点续传（0 表示尚未收到任何更新）
+    let last_seen_slot = AtomicU64::new(0);
+    let mut shutdown = shutdown;
+    let mut attempt: u32 = 0;
+
+    loop {
+        // 已收到关闭信号：不再发起新的连接
+        if *shutdown.borrow() {
+            return Ok(());
+        }
+        if attempt > 0 {
+            info!("Retry to connect to {} (attempt {})", config.endpoint, attempt);
+            stats.lock().await.record_reconnect();
+        }
+
+        match
+            run_once(&config, &request, &sink, &stats, &last_seen_slot, &mut shutdown).await
+        {
+            // 干净停止：不再重连
+            Ok(_) => return Ok(()),
+            Err(error) => {
+                error!("Connection error on {} (attempt {}): {error:#}", config.endpoint, attempt);
+                // 致命错误快速失败，避免对配置错误的端点反复重连
+                if is_permanent_error(&error) {
+                    error!("Fatal error, not retrying {}", config.endpoint);
+                    return Err(error);
+                }
+                // InvalidArgument（如 from_slot 已被裁剪）时清空续传点，下次重连不带 from_slot
+                if format!("{error:#}").to_ascii_lowercase().contains("invalidargument") {
+                    last_seen_slot.store(0, Ordering::Relaxed);
                 }
+                if attempt >= policy.max_retries as u32 {
+                    error!("Giving up on {} after {} retries", config.endpoint, attempt);
+                    return Err(error);
+                }
+            }
+        }
 
-                info!("Stream closed");
-                Ok::<(), backoff::Error<anyhow::Error>>(())
+        // 带抖动的退避；退避期间若收到关闭信号则立即中止，不再重连
+        let delay = policy.backoff_delay(attempt);
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => return Ok(()),
+            _ = tokio::time::sleep(delay) => {}
+        }
+        attempt += 1;
+    }
+}
+
+// 多端点冗余模式：每个端点一个任务，所有更新汇入一条通道，发出前经去重集合过滤
+async fn run_merged(
+    config: Config,
+    request: SubscribeRequest,
+    endpoints: Vec<String>,
+    stats: Arc<Mutex<Stats>>,
+    shutdown: watch::Receiver<bool>,
+    policy: RetryPolicy
+) -> anyhow::Result<()> {
+    let (tx, mut rx) = mpsc::channel::<SubscribeUpdate>(10_000);
+
+    for endpoint in endpoints {
+        let mut endpoint_config = config.clone();
+        endpoint_config.endpoint = endpoint.clone();
+        let request = request.clone();
+        let tx = tx.clone();
+        let stats = Arc::clone(&stats);
+        let shutdown = shutdown.clone();
+        let policy = policy.clone();
+        tokio::spawn(async move {
+            if let Err(error) =
+                run_endpoint(endpoint_config, request, Some(tx), stats, shutdown, policy).await
+            {
+                error!("Endpoint {endpoint} terminated: {error}");
             }
-        ).inspect_err(|error| error!("Connection error: {error}"))
-    }).await.map_err(Into::into)
+        });
+    }
+    drop(tx); // 仅保留各任务持有的发送端，全部退出后消费循环自然结束
+
+    let mut dedup = DedupSet::new(DEDUP_SLOT_WINDOW);
+    while let Some(update) = rx.recv().await {
+        match update_identity(&update) {
+            // 首个送达该标识的端点胜出，后续重复项丢弃
+            Some(key) if !dedup.observe(key) => continue,
+            _ => info!("Received update: {:?}", update),
+        }
+    }
+
+    Ok(())
+}
+
+// 监听 SIGINT/SIGTERM，首次收到即通过 watch 通道通知各订阅循环开始优雅关闭
+fn spawn_shutdown_listener(shutdown: watch::Sender<bool>) {
+    tokio::spawn(async move {
+        let ctrl_c = tokio::signal::ctrl_c();
+        #[cfg(unix)]
+        {
+            let mut term = match
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            {
+                Ok(term) => term,
+                Err(error) => {
+                    error!("Failed to install SIGTERM handler: {error}");
+                    return;
+                }
+            };
+            tokio::select! {
+                _ = ctrl_c => info!("Received SIGINT, shutting down"),
+                _ = term.recv() => info!("Received SIGTERM, shutting down"),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = ctrl_c.await;
+            info!("Received Ctrl-C, shutting down");
+        }
+        let _ = shutdown.send(true);
+    });
+}
+
+// 打印一条最终汇总，作为优雅关闭时刷出的收尾日志
+async fn log_final_summary(stats: &Arc<Mutex<Stats>>) {
+    let stats = stats.lock().await;
+    let avg_latency = if stats.messages == 0 {
+        0.0
+    } else {
+        stats.total_latency_ms / (stats.messages as f64)
+    };
+    info!(
+        "Shutdown summary: {} msgs, {} bytes, {} reconnects, latency avg {:.1}ms p50 {} p99 {}",
+        stats.messages,
+        stats.bytes,
+        stats.reconnects,
+        avg_latency,
+        stats.percentile(0.50),
+        stats.percentile(0.99)
+    );
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    unsafe {
+        env::set_var(
+            env_logger::DEFAULT_FILTER_ENV,
+            env::var_os(env_logger::DEFAULT_FILTER_ENV).unwrap_or_else(|| "info".into())
+        );
+    }
+
+    // 初始化日志
+    env_logger::init();
+
+    // 初始化配置
+    let config = Config::from_env()?;
+    let request = build_request(&config.filters);
+    let endpoints = config.all_endpoints();
+
+    // 启动指标采集与周期摘要
+    let stats = Arc::new(Mutex::new(Stats::default()));
+    let metrics_interval = Duration::from_secs(
+        env::var("METRICS_INTERVAL_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(METRICS_INTERVAL_SECS)
+    );
+    spawn_metrics_reporter(Arc::clone(&stats), metrics_interval);
+
+    // 监听终止信号，收到后通过 watch 通道触发优雅关闭
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    spawn_shutdown_listener(shutdown_tx);
+
+    // 有界带抖动的重连策略
+    let policy = RetryPolicy::from_env();
+
+    let result = if endpoints.len() > 1 {
+        info!("Starting merged subscription across {} endpoints", endpoints.len());
+        run_merged(config, request, endpoints, Arc::clone(&stats), shutdown_rx, policy).await
+    } else {
+        // 单端点模式也走端点列表给出的地址，避免配置了单个 YELLOWSTONE_GRPC_URLS 却连到默认端点
+        let mut config = config;
+        config.endpoint = endpoints.into_iter().next().expect("all_endpoints never empty");
+        run_endpoint(config, request, None, Arc::clone(&stats), shutdown_rx, policy).await
+    };
+
+    // 刷出收尾汇总，正常退出（返回 Ok 即退出码 0）
+    log_final_summary(&stats).await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedup_first_wins_and_rejects_duplicates() {
+        let mut dedup = DedupSet::new(512);
+        let key = UpdateKey::Slot(100, 1);
+        assert!(dedup.observe(key.clone()), "first sighting should be emitted");
+        assert!(!dedup.observe(key), "duplicate should be dropped");
+    }
+
+    #[test]
+    fn dedup_distinguishes_keys() {
+        let mut dedup = DedupSet::new(512);
+        assert!(dedup.observe(UpdateKey::Slot(1, 0)));
+        assert!(dedup.observe(UpdateKey::Slot(1, 1)), "different status is a different key");
+        assert!(dedup.observe(UpdateKey::Account(vec![1, 2, 3], 7, 1)));
+        assert!(dedup.observe(UpdateKey::Account(vec![1, 2, 3], 8, 1)), "different write_version");
+    }
+
+    #[test]
+    fn dedup_prunes_below_watermark() {
+        let mut dedup = DedupSet::new(10);
+        let old = UpdateKey::Slot(5, 0);
+        assert!(dedup.observe(old.clone()));
+        // 推进水位线使旧标识跌出窗口（5 < 100 - 10），应被逐出
+        assert!(dedup.observe(UpdateKey::Slot(100, 0)));
+        assert!(!dedup.seen.contains(&old), "stale key should be evicted");
+        // 逐出后同一旧标识会被当作首次出现重新发出
+        assert!(dedup.observe(old));
+    }
+
+    #[test]
+    fn base58_decodes_known_vectors() {
+        // 全零 pubkey 的 base58 表示为 32 个 '1'
+        assert_eq!(base58_decode(&"1".repeat(32)).unwrap(), vec![0u8; 32]);
+        // 单字节 0x00 -> "1"，0x01 -> "2"
+        assert_eq!(base58_decode("1").unwrap(), vec![0]);
+        assert_eq!(base58_decode("2").unwrap(), vec![1]);
+        // 多字节：0x0001 -> "12"（前导零保留）
+        assert_eq!(base58_decode("12").unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn base58_rejects_invalid_characters() {
+        // '0'、'O'、'I'、'l' 不在 Bitcoin 字母表中
+        assert!(base58_decode("0OIl").is_err());
+    }
+
+    #[test]
+    fn validate_pubkey_enforces_32_bytes() {
+        // 有效的 32 字节 pubkey（全零）通过校验
+        assert!(validate_pubkey(&"1".repeat(32)).is_ok());
+        // 解码后长度不足 32 字节应被拒绝
+        assert!(validate_pubkey("2").is_err());
+    }
+
+    #[test]
+    fn backoff_delay_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_millis(30_000),
+            jitter: 1.0,
+        };
+        // full-jitter 下每次取值都落在 [0, cap]，且 cap 不超过 max_backoff
+        for attempt in 0..12u32 {
+            let delay = policy.backoff_delay(attempt);
+            assert!(delay <= Duration::from_millis(30_000), "attempt {attempt} exceeded cap");
+        }
+    }
+
+    #[test]
+    fn backoff_delay_without_jitter_is_exponential() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_millis(30_000),
+            jitter: 0.0,
+        };
+        assert_eq!(policy.backoff_delay(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff_delay(1), Duration::from_millis(1_000));
+        assert_eq!(policy.backoff_delay(2), Duration::from_millis(2_000));
+        // 达到上限后被 max_backoff 钳制
+        assert_eq!(policy.backoff_delay(20), Duration::from_millis(30_000));
+    }
+
+    #[test]
+    fn invalid_argument_and_tls_are_not_fatal() {
+        // 从已裁剪的 from_slot 续传返回的 InvalidArgument 应继续重试
+        assert!(!is_permanent_error(&anyhow::anyhow!("status: InvalidArgument, message: \"slot pruned\"")));
+        // TLS 握手抖动不应被当作致命
+        assert!(!is_permanent_error(&anyhow::anyhow!("tls handshake eof")));
+        // 认证/授权错误仍然快速失败
+        assert!(is_permanent_error(&anyhow::anyhow!("status: Unauthenticated")));
+        assert!(is_permanent_error(&anyhow::anyhow!("status: PermissionDenied")));
+    }
 }